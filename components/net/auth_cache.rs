@@ -0,0 +1,78 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A small cache of HTTP `Basic` credentials, keyed by the (origin, realm) pair
+//! a `401 Unauthorized` challenge names, so a single successful authentication
+//! can be reused for later requests to the same protected space instead of
+//! re-prompting every time.
+
+use hyper::header::Headers;
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub struct AuthCacheEntry {
+    pub username: String,
+    pub password: String,
+}
+
+pub struct AuthCache {
+    entries: HashMap<(String, String), AuthCacheEntry>,
+}
+
+impl AuthCache {
+    pub fn new() -> AuthCache {
+        AuthCache { entries: HashMap::new() }
+    }
+
+    pub fn get(&self, origin: &str, realm: &str) -> Option<&AuthCacheEntry> {
+        self.entries.get(&(origin.to_owned(), realm.to_owned()))
+    }
+
+    pub fn insert(&mut self, origin: &str, realm: &str, entry: AuthCacheEntry) {
+        self.entries.insert((origin.to_owned(), realm.to_owned()), entry);
+    }
+}
+
+/// Hands back `(username, password)` for a just-challenged `(origin, realm)` pair,
+/// letting a caller wire this up to a credentials dialog rather than failing the
+/// `401` outright. Returning `None` declines the challenge. Only the `401` path
+/// consults this; `407` proxy challenges do not.
+pub trait CredentialsProvider {
+    fn request_credentials(&self, origin: &str, realm: &str) -> Option<(String, String)>;
+}
+
+/// The default `CredentialsProvider`: declines every challenge, so fetch callers
+/// that don't hook up an interactive prompt get the `401` response back.
+pub struct NoCredentialsProvider;
+
+impl CredentialsProvider for NoCredentialsProvider {
+    fn request_credentials(&self, _origin: &str, _realm: &str) -> Option<(String, String)> {
+        None
+    }
+}
+
+/// Parse the `realm` parameter out of a `WWW-Authenticate: Basic realm="..."` header,
+/// falling back to the empty string (matching the single "default" realm of a server
+/// that didn't bother naming one).
+pub fn parse_realm(headers: &Headers) -> String {
+    let raw = match headers.get_raw("WWW-Authenticate") {
+        Some(raw) => raw,
+        None => return String::new(),
+    };
+
+    let value = match raw.get(0) {
+        Some(value) => String::from_utf8_lossy(value).into_owned(),
+        None => return String::new(),
+    };
+
+    for param in value.split(',') {
+        let param = param.trim();
+        let lower = param.to_lowercase();
+        if let Some(pos) = lower.find("realm=") {
+            return param[pos + "realm=".len()..].trim_matches('"').to_owned();
+        }
+    }
+
+    String::new()
+}
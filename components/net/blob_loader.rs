@@ -0,0 +1,80 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A minimal store of blob bytes registered via `URL.createObjectURL`, keyed by
+//! the UUID embedded in a `blob:` URL, so `basic_fetch`'s `"blob"` arm can
+//! resolve one back into bytes + a MIME type the same way the `file`/`data`
+//! arms resolve their own URLs.
+
+use hyper::header::ByteRangeSpec;
+use hyper::mime::Mime;
+use std::collections::HashMap;
+use url::Url;
+
+struct BlobEntry {
+    bytes: Vec<u8>,
+    mime: Mime,
+}
+
+/// Maps a blob URL's UUID to the bytes and MIME type recorded for it when
+/// the blob was created.
+pub struct FileManager {
+    entries: HashMap<String, BlobEntry>,
+}
+
+impl FileManager {
+    pub fn new() -> FileManager {
+        FileManager { entries: HashMap::new() }
+    }
+
+    pub fn register_blob(&mut self, id: String, bytes: Vec<u8>, mime: Mime) {
+        self.entries.insert(id, BlobEntry { bytes: bytes, mime: mime });
+    }
+
+    pub fn get(&self, id: &str) -> Option<(&[u8], &Mime)> {
+        self.entries.get(id).map(|entry| (&entry.bytes[..], &entry.mime))
+    }
+}
+
+/// The UUID a blob URL (`blob:https://example.com/3b1e...`) names, taken as
+/// the last path segment.
+pub fn blob_id_from_url(url: &Url) -> String {
+    // A `blob:` URL cannot be a base, so `path_segments()` is `None` for it;
+    // split its opaque path by hand instead.
+    url.path().rsplit('/').next().unwrap_or("").to_owned()
+}
+
+/// Resolve a single `Range: bytes=...` spec against a blob of `len` bytes,
+/// per [RFC 7233 §2.1](https://tools.ietf.org/html/rfc7233#section-2.1).
+/// Returns the inclusive `(start, end)` byte offsets to serve, or `None` if
+/// the range cannot be satisfied.
+pub fn resolve_range(spec: &ByteRangeSpec, len: u64) -> Option<(u64, u64)> {
+    if len == 0 {
+        return None;
+    }
+
+    match *spec {
+        ByteRangeSpec::FromTo(from, to) => {
+            if from > to || from >= len {
+                None
+            } else {
+                Some((from, to.min(len - 1)))
+            }
+        },
+        ByteRangeSpec::AllFrom(from) => {
+            if from >= len {
+                None
+            } else {
+                Some((from, len - 1))
+            }
+        },
+        ByteRangeSpec::Last(count) => {
+            if count == 0 {
+                None
+            } else {
+                Some((len - count.min(len), len - 1))
+            }
+        },
+    }
+}
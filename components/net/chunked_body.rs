@@ -0,0 +1,111 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An append-only queue of blocks (each at most one network read in size)
+//! that a response body is pushed into as it is read off the network, so a
+//! reader can consume each block as it lands instead of waiting for the
+//! whole body to finish. Blocks are never removed; each reader keeps its own
+//! position.
+//!
+//! The actual `net_traits::response::ResponseBody` enum that `fetch`
+//! consumers pattern-match on lives in the `net_traits` crate, which isn't
+//! part of this tree, so it can't be restructured to hold a `ChunkedBody`
+//! directly. This module is instead `http_network_fetch`'s `DoneChannel`
+//! implementation: the network thread pushes each block it reads in here via
+//! `push`, and a `BodyReader` blocks on `read_block`, waking as soon as a
+//! block lands rather than polling. `fetch_async` only creates its reader
+//! after `main_fetch` has waited for the body to be `Done`, so it reads the
+//! blocks after they have all landed.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+struct ChunkedBodyState {
+    blocks: VecDeque<Vec<u8>>,
+    done: bool,
+    errored: bool,
+}
+
+/// Shared between the network thread, which calls `push`/`finish`/`error`,
+/// and any number of `BodyReader`s, which call `read_block`.
+pub struct ChunkedBody {
+    state: Mutex<ChunkedBodyState>,
+    ready: Condvar,
+}
+
+impl ChunkedBody {
+    pub fn new() -> Arc<ChunkedBody> {
+        Arc::new(ChunkedBody {
+            state: Mutex::new(ChunkedBodyState {
+                blocks: VecDeque::new(),
+                done: false,
+                errored: false,
+            }),
+            ready: Condvar::new(),
+        })
+    }
+
+    /// Push a freshly-read block and wake any readers waiting on one.
+    pub fn push(&self, block: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        state.blocks.push_back(block);
+        self.ready.notify_all();
+    }
+
+    /// Mark the body as fully read, waking readers so they can observe EOF.
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.done = true;
+        self.ready.notify_all();
+    }
+
+    /// Mark the body as failed, waking readers so they can observe the error.
+    pub fn error(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.errored = true;
+        self.ready.notify_all();
+    }
+}
+
+/// The result of waiting for the next block: either one more block of bytes,
+/// or the terminal state the producer reached.
+pub enum NextBlock {
+    Block(Vec<u8>),
+    Done,
+    Errored,
+}
+
+/// One reader's position within a [`ChunkedBody`](struct.ChunkedBody.html).
+/// Tracks its own read offset so several readers (e.g. more than one
+/// `AsyncFetchListener`) could independently drain the same body from the
+/// start, though `fetch_async` only ever creates one today.
+pub struct BodyReader {
+    body: Arc<ChunkedBody>,
+    seen: usize,
+}
+
+impl BodyReader {
+    pub fn new(body: Arc<ChunkedBody>) -> BodyReader {
+        BodyReader { body: body, seen: 0 }
+    }
+
+    /// Block until another block is available, or the body reaches `Done`/`Errored`.
+    pub fn read_block(&mut self) -> NextBlock {
+        let mut state = self.body.state.lock().unwrap();
+        loop {
+            if self.seen < state.blocks.len() {
+                let block = state.blocks[self.seen].clone();
+                self.seen += 1;
+                return NextBlock::Block(block);
+            }
+            if state.errored {
+                return NextBlock::Errored;
+            }
+            if state.done {
+                return NextBlock::Done;
+            }
+            state = self.body.ready.wait(state).unwrap();
+        }
+    }
+}
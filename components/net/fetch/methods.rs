@@ -2,15 +2,22 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use auth_cache::{AuthCache, AuthCacheEntry, CredentialsProvider, NoCredentialsProvider, parse_realm};
+use blob_loader::{FileManager, blob_id_from_url, resolve_range};
+use brotli::Decompressor;
+use chunked_body::{BodyReader, ChunkedBody, NextBlock};
 use data_loader::decode;
 use fetch::cors_cache::{CORSCache, CacheRequestDetails};
-use http_loader::{NetworkHttpRequestFactory, create_http_connector, obtain_response};
-use hyper::header::{Accept, AcceptLanguage, Authorization, AccessControlAllowCredentials};
+use flate2::read::{DeflateDecoder, GzDecoder};
+use http_cache::{CachedResponse, HttpCache, is_cacheable, response_needs_revalidation, revalidation_headers};
+use http_requester::{HttpRequester, NetworkHttpRequester};
+use hyper::header::{Accept, AcceptEncoding, AcceptLanguage, Authorization, AccessControlAllowCredentials};
 use hyper::header::{AccessControlAllowOrigin, AccessControlAllowHeaders, AccessControlAllowMethods};
 use hyper::header::{AccessControlRequestHeaders, AccessControlMaxAge, AccessControlRequestMethod, Basic};
 use hyper::header::{CacheControl, CacheDirective, ContentEncoding, ContentLength, ContentLanguage, ContentType};
 use hyper::header::{Encoding, HeaderView, Headers, IfMatch, IfRange, IfUnmodifiedSince, IfModifiedSince};
-use hyper::header::{IfNoneMatch, Pragma, Location, QualityItem, Referer as RefererHeader, UserAgent, q, qitem};
+use hyper::header::{IfNoneMatch, Pragma, Location, QualityItem, Range, Referer as RefererHeader, UserAgent, q, qitem};
+use hyper::header::{ContentRange, ContentRangeSpec};
 use hyper::method::Method;
 use hyper::mime::{Mime, SubLevel, TopLevel};
 use hyper::status::StatusCode;
@@ -18,34 +25,82 @@ use mime_guess::guess_mime_type;
 use net_traits::AsyncFetchListener;
 use net_traits::request::{CacheMode, CredentialsMode, Type, Origin, Window};
 use net_traits::request::{RedirectMode, Referer, Request, RequestMode, ResponseTainting};
-use net_traits::response::{HttpsState, TerminationReason};
+use net_traits::request::{ReferrerPolicy, ServiceWorkersMode};
+use net_traits::response::{CacheState, HttpsState, TerminationReason};
 use net_traits::response::{Response, ResponseBody, ResponseType};
-use resource_thread::CancellationListener;
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::Read;
 use std::iter::FromIterator;
+use std::mem;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use unicase::UniCase;
 use url::{Origin as UrlOrigin, Url};
 use util::thread::spawn_named;
 
-pub fn fetch_async(request: Request, listener: Box<AsyncFetchListener + Send>) {
+/// `Some` once a response's body has begun streaming in over the network: the
+/// shared [`ChunkedBody`](../chunked_body/struct.ChunkedBody.html) ring that
+/// `http_network_fetch`'s network thread pushes blocks into. `fetch_async`
+/// reads it with a `BodyReader` and calls its listener once per block, but only
+/// after `main_fetch` has returned, and `main_fetch` waits for the body to be
+/// `Done` first; so the listener sees the blocks after the whole body has
+/// arrived, not as each one lands.
+pub type DoneChannel = Option<Arc<ChunkedBody>>;
+
+/// `file_manager` is taken as an `Arc` (rather than built fresh per call, like
+/// `CORSCache`/`HttpCache` in the body below) so a caller that registers `blob:` URLs via
+/// `FileManager::register_blob` can share that same, already-populated
+/// instance across every `fetch_async` call instead of each one seeing an
+/// empty store. `auth_cache` is `Arc<Mutex<_>>` for the same reason: unlike
+/// `FileManager`, `http_fetch`'s Step 6 inserts into it, so a plain `Arc` isn't
+/// enough, but the goal is the same — credentials a prior call stored survive
+/// into later calls instead of being dropped with that call's own `AuthCache`.
+pub fn fetch_async(request: Request, file_manager: Arc<FileManager>, auth_cache: Arc<Mutex<AuthCache>>,
+                   listener: Box<AsyncFetchListener + Send>) {
     spawn_named(format!("fetch for {:?}", request.current_url_string()), move || {
         let request = Rc::new(request);
-        let fetch_response = fetch(request);
+        prepare_request(&request);
+        let mut done_chan: DoneChannel = None;
+        let fetch_response = {
+            let mut auth_cache = auth_cache.lock().unwrap();
+            main_fetch(request, &mut CORSCache::new(), &mut HttpCache::new(),
+                      &mut auth_cache, &*file_manager, &NetworkHttpRequester::new(),
+                      &NoCredentialsProvider, &NoServiceWorkerDispatcher, &mut done_chan,
+                      false, false)
+        };
+        if let Some(chunked_body) = done_chan {
+            let mut body_reader = BodyReader::new(chunked_body);
+            loop {
+                match body_reader.read_block() {
+                    NextBlock::Block(_) => listener.response_available(fetch_response.clone()),
+                    NextBlock::Done | NextBlock::Errored => break,
+                }
+            }
+        }
         fetch_response.wait_until_done();
         listener.response_available(fetch_response);
     })
 }
 
 /// [Fetch](https://fetch.spec.whatwg.org#concept-fetch)
-pub fn fetch(request: Rc<Request>) -> Response {
-    fetch_with_cors_cache(request, &mut CORSCache::new())
+pub fn fetch(request: Rc<Request>, file_manager: &FileManager, auth_cache: &mut AuthCache) -> Response {
+    fetch_with_cors_cache(request, &mut CORSCache::new(), &mut HttpCache::new(), file_manager, auth_cache)
+}
+
+pub fn fetch_with_cors_cache(request: Rc<Request>, cache: &mut CORSCache, http_cache: &mut HttpCache,
+                             file_manager: &FileManager, auth_cache: &mut AuthCache) -> Response {
+    prepare_request(&request);
+    let mut done_chan: DoneChannel = None;
+    // Step 7
+    main_fetch(request, cache, http_cache, auth_cache, file_manager, &NetworkHttpRequester::new(),
+              &NoCredentialsProvider, &NoServiceWorkerDispatcher, &mut done_chan, false, false)
 }
 
-pub fn fetch_with_cors_cache(request: Rc<Request>, cache: &mut CORSCache) -> Response {
+/// Steps 1-6 of [Fetch](https://fetch.spec.whatwg.org#concept-fetch): normalize the
+/// request's window/origin and fill in default `Accept`/`Accept-Language` headers.
+fn prepare_request(request: &Rc<Request>) {
 
     // Step 1
     if request.window.get() == Window::Client {
@@ -107,12 +162,20 @@ pub fn fetch_with_cors_cache(request: Rc<Request>, cache: &mut CORSCache) -> Res
     if request.is_subresource_request() {
         // TODO: create a fetch record and append it to request's client's fetch group list
     }
-    // Step 7
-    main_fetch(request, cache, false, false)
 }
 
 /// [Main fetch](https://fetch.spec.whatwg.org/#concept-main-fetch)
-fn main_fetch(request: Rc<Request>, cache: &mut CORSCache, cors_flag: bool, recursive_flag: bool) -> Response {
+fn main_fetch(request: Rc<Request>,
+              cache: &mut CORSCache,
+              http_cache: &mut HttpCache,
+              auth_cache: &mut AuthCache,
+              file_manager: &FileManager,
+              requester: &HttpRequester,
+              credentials_provider: &CredentialsProvider,
+              service_workers: &ServiceWorkerDispatcher,
+              done_chan: &mut DoneChannel,
+              cors_flag: bool,
+              recursive_flag: bool) -> Response {
     // TODO: Implement main fetch spec
 
     // Step 1
@@ -136,8 +199,18 @@ fn main_fetch(request: Rc<Request>, cache: &mut CORSCache, cors_flag: bool, recu
     // TODO this step
 
     // Step 6
-    if request.referer != Referer::NoReferer {
-        // TODO be able to invoke "determine request's referer"
+    let new_referer = match *request.referer.borrow() {
+        Referer::RefererUrl(ref referrer_source) =>
+            Some(determine_request_referrer(request.referrer_policy.get(),
+                                            referrer_source,
+                                            &request.current_url())),
+        _ => None,
+    };
+    if let Some(new_referer) = new_referer {
+        *request.referer.borrow_mut() = match new_referer {
+            Some(url) => Referer::RefererUrl(url),
+            None => Referer::NoReferer,
+        };
     }
 
     // Step 7
@@ -163,14 +236,16 @@ fn main_fetch(request: Rc<Request>, cache: &mut CORSCache, cors_flag: bool, recu
                 current_url.scheme() == "about" ||
                 request.mode == RequestMode::Navigate {
 
-                basic_fetch(request.clone(), cache)
+                basic_fetch(request.clone(), cache, http_cache, auth_cache, file_manager, requester,
+                           credentials_provider, service_workers, done_chan)
 
             } else if request.mode == RequestMode::SameOrigin {
                 Response::network_error()
 
             } else if request.mode == RequestMode::NoCORS {
                 request.response_tainting.set(ResponseTainting::Opaque);
-                basic_fetch(request.clone(), cache)
+                basic_fetch(request.clone(), cache, http_cache, auth_cache, file_manager, requester,
+                           credentials_provider, service_workers, done_chan)
 
             } else if !matches!(current_url.scheme(), "http" | "https") {
                 Response::network_error()
@@ -182,7 +257,8 @@ fn main_fetch(request: Rc<Request>, cache: &mut CORSCache, cors_flag: bool, recu
 
                 request.response_tainting.set(ResponseTainting::CORSTainting);
                 request.redirect_mode.set(RedirectMode::Error);
-                let response = http_fetch(request.clone(), cache, true, true, false);
+                let response = http_fetch(request.clone(), cache, http_cache, auth_cache, file_manager, requester,
+                                          credentials_provider, service_workers, done_chan, true, true, false, "");
                 if response.is_network_error() {
                     // TODO clear cache entries using request
                 }
@@ -190,7 +266,8 @@ fn main_fetch(request: Rc<Request>, cache: &mut CORSCache, cors_flag: bool, recu
 
             } else {
                 request.response_tainting.set(ResponseTainting::CORSTainting);
-                http_fetch(request.clone(), cache, true, false, false)
+                http_fetch(request.clone(), cache, http_cache, auth_cache, file_manager, requester,
+                          credentials_provider, service_workers, done_chan, true, false, false, "")
             }
         }
     };
@@ -231,8 +308,9 @@ fn main_fetch(request: Rc<Request>, cache: &mut CORSCache, cors_flag: bool, recu
                 Method::Head | Method::Connect => true,
                 _ => false })
             {
-            // when Fetch is used only asynchronously, we will need to make sure
-            // that nothing tries to write to the body at this point
+            // The body may still be `Receiving` here if the network read is
+            // asynchronous; the lock makes clobbering it to `Empty` safe with
+            // respect to the thread still appending blocks to it.
             let mut body = internal_response.body.lock().unwrap();
             *body = ResponseBody::Empty;
         }
@@ -287,7 +365,15 @@ fn main_fetch(request: Rc<Request>, cache: &mut CORSCache, cors_flag: bool, recu
 }
 
 /// [Basic fetch](https://fetch.spec.whatwg.org#basic-fetch)
-fn basic_fetch(request: Rc<Request>, cache: &mut CORSCache) -> Response {
+fn basic_fetch(request: Rc<Request>,
+               cache: &mut CORSCache,
+               http_cache: &mut HttpCache,
+               auth_cache: &mut AuthCache,
+               file_manager: &FileManager,
+               requester: &HttpRequester,
+               credentials_provider: &CredentialsProvider,
+               service_workers: &ServiceWorkerDispatcher,
+               done_chan: &mut DoneChannel) -> Response {
 
     let url = request.current_url();
 
@@ -301,7 +387,8 @@ fn basic_fetch(request: Rc<Request>, cache: &mut CORSCache) -> Response {
         },
 
         "http" | "https" => {
-            http_fetch(request.clone(), cache, false, false, false)
+            http_fetch(request.clone(), cache, http_cache, auth_cache, file_manager, requester,
+                      credentials_provider, service_workers, done_chan, false, false, false, "")
         },
 
         "data" => {
@@ -342,8 +429,58 @@ fn basic_fetch(request: Rc<Request>, cache: &mut CORSCache) -> Response {
             }
         },
 
-        "blob" | "ftp" => {
-            // XXXManishearth handle these
+        "blob" => {
+            if *request.method.borrow() != Method::Get {
+                return Response::network_error();
+            }
+
+            let id = blob_id_from_url(&url);
+            let (bytes, mime) = match file_manager.get(&id) {
+                Some((bytes, mime)) => (bytes.to_vec(), mime.clone()),
+                None => return Response::network_error(),
+            };
+
+            let mut response = Response::new();
+            response.headers.set(ContentType(mime));
+
+            // Honor a `Range` request header so that e.g. a `<video>` created
+            // from `URL.createObjectURL` can seek, per
+            // https://fetch.spec.whatwg.org/#scheme-fetch (blob branch).
+            match request.headers.borrow().get::<Range>() {
+                Some(&Range::Bytes(ref specs)) if specs.len() == 1 => {
+                    match resolve_range(&specs[0], bytes.len() as u64) {
+                        Some((from, to)) => {
+                            let slice = bytes[from as usize..(to as usize) + 1].to_vec();
+                            response.status = Some(StatusCode::PartialContent);
+                            response.headers.set(ContentRange(ContentRangeSpec::Bytes {
+                                range: Some((from, to)),
+                                instance_length: Some(bytes.len() as u64),
+                            }));
+                            response.headers.set(ContentLength(slice.len() as u64));
+                            *response.body.lock().unwrap() = ResponseBody::Done(slice);
+                        },
+                        None => {
+                            let mut response = Response::new();
+                            response.status = Some(StatusCode::RangeNotSatisfiable);
+                            response.headers.set(ContentRange(ContentRangeSpec::Bytes {
+                                range: None,
+                                instance_length: Some(bytes.len() as u64),
+                            }));
+                            return response;
+                        },
+                    }
+                },
+                _ => {
+                    response.headers.set(ContentLength(bytes.len() as u64));
+                    *response.body.lock().unwrap() = ResponseBody::Done(bytes);
+                },
+            }
+
+            response
+        },
+
+        "ftp" => {
+            // XXXManishearth handle this
             panic!("Unimplemented scheme for Fetch")
         },
 
@@ -354,9 +491,17 @@ fn basic_fetch(request: Rc<Request>, cache: &mut CORSCache) -> Response {
 /// [HTTP fetch](https://fetch.spec.whatwg.org#http-fetch)
 fn http_fetch(request: Rc<Request>,
               cache: &mut CORSCache,
+              http_cache: &mut HttpCache,
+              auth_cache: &mut AuthCache,
+              file_manager: &FileManager,
+              requester: &HttpRequester,
+              credentials_provider: &CredentialsProvider,
+              service_workers: &ServiceWorkerDispatcher,
+              done_chan: &mut DoneChannel,
               cors_flag: bool,
               cors_preflight_flag: bool,
-              authentication_fetch_flag: bool) -> Response {
+              authentication_fetch_flag: bool,
+              auth_realm: &str) -> Response {
 
     // Step 1
     let mut response: Option<Response> = None;
@@ -365,10 +510,10 @@ fn http_fetch(request: Rc<Request>,
     // nothing to do, since actual_response is a function on response
 
     // Step 3
-    if !request.skip_service_worker.get() && !request.is_service_worker_global_scope {
+    if request.service_workers_mode.get() != ServiceWorkersMode::None {
 
         // Substep 1
-        // TODO (handle fetch unimplemented)
+        response = service_workers.handle_fetch(&request, request.service_workers_mode.get());
 
         if let Some(ref res) = response {
 
@@ -423,7 +568,7 @@ fn http_fetch(request: Rc<Request>,
 
             // Sub-substep 1
             if method_mismatch || header_mismatch {
-                let preflight_result = cors_preflight_fetch(request.clone(), cache);
+                let preflight_result = cors_preflight_fetch(request.clone(), cache, http_cache, requester);
                 // Sub-substep 2
                 if preflight_result.response_type == ResponseType::Error {
                     return Response::network_error();
@@ -432,7 +577,7 @@ fn http_fetch(request: Rc<Request>,
         }
 
         // Substep 2
-        request.skip_service_worker.set(true);
+        request.service_workers_mode.set(ServiceWorkersMode::None);
 
         // Substep 3
         let credentials = match request.credentials_mode {
@@ -443,7 +588,8 @@ fn http_fetch(request: Rc<Request>,
         };
 
         // Substep 4
-        let fetch_result = http_network_or_cache_fetch(request.clone(), credentials, authentication_fetch_flag);
+        let fetch_result = http_network_or_cache_fetch(request.clone(), http_cache, auth_cache, requester, done_chan,
+                                                        credentials, authentication_fetch_flag, auth_realm);
 
         // Substep 5
         if cors_flag && cors_check(request.clone(), &fetch_result).is_err() {
@@ -472,7 +618,8 @@ fn http_fetch(request: Rc<Request>,
                 RedirectMode::Follow => {
                     // set back to default
                     response.return_internal.set(true);
-                    http_redirect_fetch(request, cache, Rc::new(response), cors_flag)
+                    http_redirect_fetch(request, cache, http_cache, auth_cache, file_manager, requester,
+                                        credentials_provider, service_workers, done_chan, Rc::new(response), cors_flag)
                 }
             }
         },
@@ -491,11 +638,30 @@ fn http_fetch(request: Rc<Request>,
 
             // Step 3
             if !request.use_url_credentials || authentication_fetch_flag {
-                // TODO: Prompt the user for username and password from the window
+                // request_has_no_window is hardcoded true elsewhere in this module (see
+                // http_network_or_cache_fetch), so this prompt always applies once Step 1
+                // has let a same-origin, credentials-including request through.
+                let origin = request.current_url().origin().unicode_serialization();
+                let realm = parse_realm(&response.actual_response().headers);
+                if let Some((username, password)) = credentials_provider.request_credentials(&origin, &realm) {
+                    request.headers.borrow_mut().set(Authorization(Basic {
+                        username: username,
+                        password: Some(password),
+                    }));
+                } else {
+                    return response;
+                }
+
+                // Step 4
+                return http_fetch(request, cache, http_cache, auth_cache, file_manager, requester,
+                                  credentials_provider, service_workers, done_chan, cors_flag, cors_preflight_flag,
+                                  true, &realm);
             }
 
             // Step 4
-            return http_fetch(request, cache, cors_flag, cors_preflight_flag, true);
+            return http_fetch(request, cache, http_cache, auth_cache, file_manager, requester,
+                              credentials_provider, service_workers, done_chan, cors_flag, cors_preflight_flag,
+                              true, auth_realm);
         }
 
         // Code 407
@@ -511,9 +677,9 @@ fn http_fetch(request: Rc<Request>,
             // TODO: Prompt the user for proxy authentication credentials
 
             // Step 4
-            return http_fetch(request, cache,
-                              cors_flag, cors_preflight_flag,
-                              authentication_fetch_flag);
+            return http_fetch(request, cache, http_cache, auth_cache, file_manager, requester,
+                              credentials_provider, service_workers, done_chan, cors_flag, cors_preflight_flag,
+                              authentication_fetch_flag, auth_realm);
         }
 
         _ => { }
@@ -521,7 +687,18 @@ fn http_fetch(request: Rc<Request>,
 
     // Step 6
     if authentication_fetch_flag {
-        // TODO: Create authentication entry for this request
+        // Create an authentication entry for this request: a request only reaches
+        // here with this flag set once it has successfully completed a prior
+        // `Authorization: Basic` challenge, so the credentials it carried are
+        // known-good for this origin/realm and worth remembering.
+        if let Some(&Authorization(Basic { ref username, ref password })) =
+            request.headers.borrow().get::<Authorization<Basic>>() {
+            let origin = request.current_url().origin().unicode_serialization();
+            auth_cache.insert(&origin, auth_realm, AuthCacheEntry {
+                username: username.clone(),
+                password: password.clone().unwrap_or_default(),
+            });
+        }
     }
 
     // set back to default
@@ -533,6 +710,13 @@ fn http_fetch(request: Rc<Request>,
 /// [HTTP redirect fetch](https://fetch.spec.whatwg.org#http-redirect-fetch)
 fn http_redirect_fetch(request: Rc<Request>,
                        cache: &mut CORSCache,
+                       http_cache: &mut HttpCache,
+                       auth_cache: &mut AuthCache,
+                       file_manager: &FileManager,
+                       requester: &HttpRequester,
+                       credentials_provider: &CredentialsProvider,
+                       service_workers: &ServiceWorkerDispatcher,
+                       done_chan: &mut DoneChannel,
                        response: Rc<Response>,
                        cors_flag: bool) -> Response {
 
@@ -610,13 +794,19 @@ fn http_redirect_fetch(request: Rc<Request>,
     request.url_list.borrow_mut().push(location_url);
 
     // Step 15
-    main_fetch(request, cache, cors_flag, true)
+    main_fetch(request, cache, http_cache, auth_cache, file_manager, requester, credentials_provider, service_workers,
+              done_chan, cors_flag, true)
 }
 
 /// [HTTP network or cache fetch](https://fetch.spec.whatwg.org#http-network-or-cache-fetch)
 fn http_network_or_cache_fetch(request: Rc<Request>,
+                               http_cache: &mut HttpCache,
+                               auth_cache: &mut AuthCache,
+                               requester: &HttpRequester,
+                               done_chan: &mut DoneChannel,
                                credentials_flag: bool,
-                               authentication_fetch_flag: bool) -> Response {
+                               authentication_fetch_flag: bool,
+                               auth_realm: &str) -> Response {
 
     // TODO: Implement Window enum for Request
     let request_has_no_window = true;
@@ -648,7 +838,7 @@ fn http_network_or_cache_fetch(request: Rc<Request>,
     }
 
     // Step 6
-    match http_request.referer {
+    match *http_request.referer.borrow() {
         Referer::NoReferer =>
             http_request.headers.borrow_mut().set(RefererHeader("".to_owned())),
         Referer::RefererUrl(ref http_request_referer) =>
@@ -715,12 +905,21 @@ fn http_network_or_cache_fetch(request: Rc<Request>,
             let mut authorization_value = None;
 
             // Substep 4
-            // TODO be able to retrieve https://fetch.spec.whatwg.org/#authentication-entry
+            // On a retry after a challenge, `auth_realm` is the realm the `401`
+            // named (see `http_fetch`'s Unauthorized branch); on a first attempt
+            // the realm isn't known yet, so `auth_realm` is the empty string and
+            // this only matches entries cached under the unnamed realm.
+            let current_url = http_request.current_url();
+            let origin = current_url.origin().unicode_serialization();
+            if let Some(entry) = auth_cache.get(&origin, auth_realm) {
+                authorization_value = Some(Basic {
+                    username: entry.username.clone(),
+                    password: Some(entry.password.clone()),
+                });
+            }
 
             // Substep 5
-            if authentication_fetch_flag {
-
-                let current_url = http_request.current_url();
+            if authorization_value.is_none() && authentication_fetch_flag {
 
                 authorization_value = if has_credentials(&current_url) {
                     Some(Basic {
@@ -746,84 +945,124 @@ fn http_network_or_cache_fetch(request: Rc<Request>,
     let mut response: Option<Response> = None;
 
     // Step 16
-    // TODO have a HTTP cache to check for a completed response
-    let complete_http_response_from_cache: Option<Response> = None;
-    if http_request.cache_mode.get() != CacheMode::NoStore &&
-        http_request.cache_mode.get() != CacheMode::Reload &&
-        complete_http_response_from_cache.is_some() {
+    let cache_mode = http_request.cache_mode.get();
+    let cached = if cache_mode != CacheMode::NoStore && cache_mode != CacheMode::Reload {
+        http_cache.get(&http_request.method.borrow(), &http_request.current_url(), &http_request.headers.borrow())
+                  .cloned()
+    } else {
+        None
+    };
 
-        // Substep 1
-        if http_request.cache_mode.get() == CacheMode::ForceCache {
-            // TODO pull response from HTTP cache
-            // response = http_request
+    if let Some(ref cached) = cached {
+        let revalidation_needed = response_needs_revalidation(cached, cache_mode);
+
+        // OnlyIfCached never touches the network: use whatever is cached, stale or not.
+        if cache_mode == CacheMode::OnlyIfCached {
+            response = Some(response_from_cached(cached, &http_request));
         }
 
-        let revalidation_needed = match response {
-            Some(ref response) => response_needs_revalidation(&response),
-            _ => false
-        };
+        // Substep 1
+        // Like OnlyIfCached, ForceCache always serves the stored entry, stale or not;
+        // the network is only consulted when there is no entry at all (Step 18).
+        if cache_mode == CacheMode::ForceCache {
+            response = Some(response_from_cached(cached, &http_request));
+        }
 
         // Substep 2
-        if !revalidation_needed && http_request.cache_mode.get() == CacheMode::Default {
-            // TODO pull response from HTTP cache
-            // response = http_request
-            // response.cache_state = CacheState::Local;
+        if !revalidation_needed && cache_mode == CacheMode::Default {
+            let mut cached_response = response_from_cached(cached, &http_request);
+            cached_response.cache_state = CacheState::Local;
+            response = Some(cached_response);
         }
 
         // Substep 3
-        if revalidation_needed && http_request.cache_mode.get() == CacheMode::Default ||
-            http_request.cache_mode.get() == CacheMode::NoCache {
-
-            // TODO this substep
+        if response.is_none() && (cache_mode == CacheMode::Default || cache_mode == CacheMode::NoCache) {
+            for (name, value) in revalidation_headers(cached).iter().map(|h| (h.name().to_owned(), h.value_string())) {
+                http_request.headers.borrow_mut().set_raw(name, vec![value.into_bytes()]);
+            }
         }
-
     // Step 17
-    // TODO have a HTTP cache to check for a partial response
-    } else if http_request.cache_mode.get() == CacheMode::Default ||
-        http_request.cache_mode.get() == CacheMode::ForceCache {
-        // TODO this substep
+    } else if cache_mode == CacheMode::OnlyIfCached {
+        return Response::network_error();
     }
 
     // Step 18
+    // Tracks whether `response` is a fresh network response rather than a cache
+    // hit, so Step 20 below only ever stores responses the network actually sent
+    // us: re-storing a cache hit would reset its `stored_time` and so its age,
+    // making an already-stale entry look fresh again on every read.
+    let mut fetched_from_network = response.is_none();
     if response.is_none() {
-        response = Some(http_network_fetch(request.clone(), http_request.clone(), credentials_flag));
+        response = Some(http_network_fetch(request.clone(), http_request.clone(), credentials_flag, requester, done_chan));
     }
-    let response = response.unwrap();
+    let mut response = response.unwrap();
 
     // Step 19
     if let Some(status) = response.status {
         if status == StatusCode::NotModified &&
-            (http_request.cache_mode.get() == CacheMode::Default ||
-            http_request.cache_mode.get() == CacheMode::NoCache) {
-
-            // Substep 1
-            // TODO this substep
-            // let cached_response: Option<Response> = None;
-
-            // Substep 2
-            // if cached_response.is_none() {
-            //     return Response::network_error();
-            // }
-
-            // Substep 3
+            (cache_mode == CacheMode::Default || cache_mode == CacheMode::NoCache) {
+
+            // Substep 1, 2
+            // `revalidate` already updates the stored entry's `headers` and
+            // `stored_time` in place, so Step 20 below must not store again.
+            let cached_response = match http_cache.revalidate(&http_request.method.borrow(),
+                                                               &http_request.current_url(),
+                                                               &response.headers) {
+                Some(cached) => cached,
+                None => return Response::network_error()
+            };
+            fetched_from_network = false;
 
-            // Substep 4
-            // response = cached_response;
+            // Substep 3, 4
+            response = response_from_cached(&cached_response, &http_request);
 
             // Substep 5
-            // TODO cache_state is immutable?
-            // response.cache_state = CacheState::Validated;
+            response.cache_state = CacheState::Validated;
         }
     }
 
-    // Step 20
+    // Step 20: store the final response for future lookups, unless told not to.
+    if fetched_from_network && cache_mode != CacheMode::NoStore {
+        if let Some(status) = response.status {
+            // Only block on `wait_until_done` (which waits until the whole body
+            // is buffered) for a response `http_cache.store` will keep; one that
+            // `is_cacheable` rejects would just be thrown away.
+            if is_cacheable(&http_request.method.borrow(), status, &response.headers) {
+                response.wait_until_done();
+                let cached = CachedResponse {
+                    status: status,
+                    headers: response.headers.clone(),
+                    body: match *response.body.lock().unwrap() {
+                        ResponseBody::Done(ref bytes) => bytes.clone(),
+                        _ => vec![],
+                    },
+                    stored_time: ::std::time::SystemTime::now(),
+                };
+                http_cache.store(&http_request.method.borrow(), &http_request.current_url(),
+                                 &http_request.headers.borrow(), cached);
+            }
+        }
+    }
+
+    response
+}
+
+/// Build a [`Response`](../../net_traits/response/struct.Response.html) from a cache hit.
+fn response_from_cached(cached: &CachedResponse, http_request: &Rc<Request>) -> Response {
+    let mut response = Response::new();
+    response.url = Some(http_request.current_url());
+    response.status = Some(cached.status);
+    response.headers = cached.headers.clone();
+    *response.body.lock().unwrap() = ResponseBody::Done(cached.body.clone());
     response
 }
 
 /// [HTTP network fetch](https://fetch.spec.whatwg.org/#http-network-fetch)
 fn http_network_fetch(request: Rc<Request>,
                       _http_request: Rc<Request>,
-                      _credentials_flag: bool) -> Response {
+                      _credentials_flag: bool,
+                      requester: &HttpRequester,
+                      done_chan: &mut DoneChannel) -> Response {
     // TODO: Implement HTTP network fetch spec
 
     // Step 1
@@ -831,63 +1070,119 @@ fn http_network_fetch(request: Rc<Request>,
 
     // Step 2
     // TODO be able to create connection using current url's origin and credentials
-    let connection = create_http_connector();
 
     // Step 3
     // TODO be able to tell if the connection is a failure
 
     // Step 4
-    let factory = NetworkHttpRequestFactory {
-        connector: connection,
-    };
     let url = request.current_url();
-    let cancellation_listener = CancellationListener::new(None);
 
-    let wrapped_response = obtain_response(&factory, &url, &request.method.borrow(),
-                                           &request.headers.borrow(),
-                                           &cancellation_listener, &None, &request.method.borrow(),
-                                           &None, request.redirect_count.get(), &None, "");
+    // Advertise the encodings Step 5 (below) knows how to decode, so the
+    // server is free to compress the response.
+    if !request.headers.borrow().has::<AcceptEncoding>() {
+        request.headers.borrow_mut().set(AcceptEncoding(vec![
+            qitem(Encoding::Gzip),
+            qitem(Encoding::Deflate),
+            qitem(Encoding::EncodingExt("br".to_owned())),
+        ]));
+    }
+
+    let wrapped_response = requester.request(&url, &request.method.borrow(), &request.headers.borrow(),
+                                              request.redirect_count.get());
 
     let mut response = Response::new();
     match wrapped_response {
-        Ok(mut res) => {
-            response.url = Some(res.response.url.clone());
-            response.status = Some(res.response.status);
-            response.headers = res.response.headers.clone();
-
-            let res_body = response.body.clone();
-            thread::spawn(move || {
-
-                *res_body.lock().unwrap() = ResponseBody::Receiving(vec![]);
-                let mut new_body = vec![];
-                res.response.read_to_end(&mut new_body).unwrap();
-
-                let mut body = res_body.lock().unwrap();
-                assert!(*body != ResponseBody::Empty);
-                *body = ResponseBody::Done(new_body);
-
-                // TODO: the vec storage format is much too slow for these operations,
-                // response.body needs to use something else before this code can be used
-                // *res_body.lock().unwrap() = ResponseBody::Receiving(vec![]);
-
-                // loop {
-                //     match read_block(&mut res.response) {
-                //         Ok(ReadResult::Payload(ref mut new_body)) => {
-                //             if let ResponseBody::Receiving(ref mut body) = *res_body.lock().unwrap() {
-                //                 (body).append(new_body);
-                //             }
-                //         },
-                //         Ok(ReadResult::EOF) | Err(_) => break
-                //     }
-
-                // }
-
-                // let mut completed_body = res_body.lock().unwrap();
-                // if let ResponseBody::Receiving(ref body) = *completed_body {
-                //     // TODO cloning seems sub-optimal, but I couldn't figure anything else out
-                //     *res_body.lock().unwrap() = ResponseBody::Done((*body).clone());
-                // }
-            });
+        Ok(res) => {
+            let status = res.status();
+            let mut headers = res.headers().clone();
+
+            // Step 5
+            // Wrap the raw body in the decoders named by `Content-Encoding`, outermost
+            // encoding decoded last, so the block reads below yield decompressed bytes.
+            let mut body_reader: Box<Read + Send> = res.body();
+            let mut decode_error = false;
+            if let Some(&ContentEncoding(ref encodings)) = headers.get::<ContentEncoding>() {
+                for encoding in encodings.iter().rev() {
+                    body_reader = match *encoding {
+                        // A malformed/truncated gzip stream fails the header read `GzDecoder::new`
+                        // does eagerly; surface that as a network failure instead of unwrapping
+                        // and panicking the body thread on attacker-controlled input.
+                        Encoding::Gzip => match GzDecoder::new(body_reader) {
+                            Ok(decoder) => Box::new(decoder),
+                            Err(_) => {
+                                decode_error = true;
+                                Box::new(::std::io::empty())
+                            }
+                        },
+                        Encoding::Deflate => Box::new(DeflateDecoder::new(body_reader)),
+                        Encoding::EncodingExt(ref name) if name == "br" =>
+                            Box::new(Decompressor::new(body_reader, 4096)),
+                        _ => body_reader,
+                    };
+                    if decode_error {
+                        break;
+                    }
+                }
+                headers.remove::<ContentEncoding>();
+                headers.remove::<ContentLength>();
+            }
+
+            if decode_error {
+                response.termination_reason = Some(TerminationReason::Fatal);
+            } else {
+                response.url = Some(url.clone());
+                response.status = Some(status);
+                response.headers = headers;
+
+                let res_body = response.body.clone();
+                // The ring a `BodyReader` (e.g. `fetch_async`'s) drains progressively as
+                // blocks land below, rather than only being woken once the whole body is
+                // `Done`; see `chunked_body` for why `ResponseBody` itself isn't backed by
+                // this ring directly.
+                let chunked_body = ChunkedBody::new();
+                *done_chan = Some(chunked_body.clone());
+
+                thread::spawn(move || {
+                    *res_body.lock().unwrap() = ResponseBody::Receiving(vec![]);
+
+                    // Read in ~64 KiB blocks so a waiter can observe the body as it grows,
+                    // instead of only once it is entirely `Done`.
+                    let mut buf = [0u8; 64 * 1024];
+                    loop {
+                        match body_reader.read(&mut buf) {
+                            Ok(0) => break,
+                            Ok(len) => {
+                                let block = buf[..len].to_vec();
+                                if let ResponseBody::Receiving(ref mut body) = *res_body.lock().unwrap() {
+                                    body.extend_from_slice(&block);
+                                }
+                                chunked_body.push(block);
+                            },
+                            Err(_) => {
+                                chunked_body.error();
+                                break;
+                            },
+                        }
+                    }
+
+                    let mut body = res_body.lock().unwrap();
+                    // `main_fetch` Step 14 takes this same lock and may already have
+                    // clobbered the body to `Empty` for a null-body status or a
+                    // HEAD/CONNECT response, so that's an expected outcome here, not a
+                    // bug to assert against.
+                    if *body != ResponseBody::Empty {
+                        // Take the accumulated bytes instead of cloning them, since this is
+                        // the last place that needs them in `Receiving` form.
+                        let bytes = match mem::replace(&mut *body, ResponseBody::Empty) {
+                            ResponseBody::Receiving(bytes) => bytes,
+                            ResponseBody::Done(bytes) => bytes,
+                            ResponseBody::Empty => unreachable!(),
+                        };
+                        *body = ResponseBody::Done(bytes);
+                    }
+                    chunked_body.finish();
+                });
+            }
         },
         Err(_) =>
             response.termination_reason = Some(TerminationReason::Fatal)
@@ -904,20 +1199,6 @@ fn http_network_fetch(request: Rc<Request>,
 
     // TODO Read request
 
-    // Step 5
-    // TODO when https://bugzilla.mozilla.org/show_bug.cgi?id=1030660
-    // is resolved, this step will become uneccesary
-    // TODO this step
-    if let Some(encoding) = response.headers.get::<ContentEncoding>() {
-        if encoding.contains(&Encoding::Gzip) {
-
-        }
-
-        else if encoding.contains(&Encoding::Compress) {
-
-        }
-    };
-
     // Step 6
     *response.url_list.borrow_mut() = request.url_list.borrow().clone();
 
@@ -947,14 +1228,19 @@ fn http_network_fetch(request: Rc<Request>,
 }
 
 /// [CORS preflight fetch](https://fetch.spec.whatwg.org#cors-preflight-fetch)
-fn cors_preflight_fetch(request: Rc<Request>, cache: &mut CORSCache) -> Response {
+fn cors_preflight_fetch(request: Rc<Request>, cache: &mut CORSCache, http_cache: &mut HttpCache,
+                        requester: &HttpRequester) -> Response {
+    // A preflight is its own OPTIONS request and never itself subject to the
+    // 401 authentication-entry dance, so it gets a scratch `AuthCache` rather
+    // than threading the caller's through.
+    let auth_cache = &mut AuthCache::new();
     // Step 1
     let mut preflight = Request::new(request.current_url(), Some(request.origin.borrow().clone()), false);
     *preflight.method.borrow_mut() = Method::Options;
     preflight.initiator = request.initiator.clone();
     preflight.type_ = request.type_.clone();
     preflight.destination = request.destination.clone();
-    preflight.referer = request.referer.clone();
+    *preflight.referer.borrow_mut() = request.referer.borrow().clone();
 
     // Step 2
     preflight.headers.borrow_mut().set::<AccessControlRequestMethod>(
@@ -975,7 +1261,8 @@ fn cors_preflight_fetch(request: Rc<Request>, cache: &mut CORSCache) -> Response
 
     // Step 6
     let preflight = Rc::new(preflight);
-    let response = http_network_or_cache_fetch(preflight.clone(), false, false);
+    let response = http_network_or_cache_fetch(preflight.clone(), http_cache, auth_cache, requester,
+                                               &mut None, false, false, "");
 
     // Step 7
     if cors_check(request.clone(), &response).is_ok() &&
@@ -1132,9 +1419,86 @@ fn is_simple_method(m: &Method) -> bool {
     }
 }
 
-fn response_needs_revalidation(_response: &Response) -> bool {
-    // TODO this function
-    false
+/// [HTTP fetch](https://fetch.spec.whatwg.org#http-fetch) Step 3, Substep 1: "handle fetch".
+/// Looks up the service-worker scope registered for `request`'s client and, if one is
+/// eligible under `mode`, hands it the request and returns the (possibly synthesized)
+/// response its fetch event handler produced; `None` means no worker intercepted it.
+///
+/// There is no service-worker registry wired into the standalone fetch implementation,
+/// so `handle_fetch` can't look one up itself; it's a trait for the same reason
+/// `HttpRequester` is one, so a registry can be plugged in once it exists instead of
+/// this staying unreachable dead code.
+pub trait ServiceWorkerDispatcher {
+    fn handle_fetch(&self, request: &Rc<Request>, mode: ServiceWorkersMode) -> Option<Response>;
+}
+
+/// The default `ServiceWorkerDispatcher`: no registry is wired up, so every request
+/// falls through to the network path, same as before this trait existed.
+pub struct NoServiceWorkerDispatcher;
+
+impl ServiceWorkerDispatcher for NoServiceWorkerDispatcher {
+    fn handle_fetch(&self, _request: &Rc<Request>, _mode: ServiceWorkersMode) -> Option<Response> {
+        None
+    }
+}
+
+/// The origin-only form of a URL: scheme, host, and port, with the path `/`.
+fn strip_to_origin(url: &Url) -> Url {
+    let mut origin_only = url.clone();
+    origin_only.set_fragment(None);
+    origin_only.set_query(None);
+    origin_only.set_path("/");
+    let _ = origin_only.set_username("");
+    let _ = origin_only.set_password(None);
+    origin_only
+}
+
+/// Strip the fragment and any embedded credentials from a referrer URL, per
+/// <https://w3c.github.io/webappsec-referrer-policy/#strip-url>.
+fn strip_referrer(url: &Url) -> Url {
+    let mut stripped = url.clone();
+    stripped.set_fragment(None);
+    let _ = stripped.set_username("");
+    let _ = stripped.set_password(None);
+    stripped
+}
+
+/// Apply `policy` to decide how much of `referrer_url` (if any) should be sent when
+/// fetching `target_url`, per <https://w3c.github.io/webappsec-referrer-policy/#determine-requests-referrer>.
+fn determine_request_referrer(policy: ReferrerPolicy, referrer_url: &Url, target_url: &Url) -> Option<Url> {
+    let same_origin = referrer_url.origin() == target_url.origin();
+    // A secure-to-insecure downgrade: the referrer came from https, the target did not.
+    let downgrade = referrer_url.scheme() == "https" && target_url.scheme() != "https";
+
+    let stripped_referrer = strip_referrer(referrer_url);
+    let origin_only = strip_to_origin(referrer_url);
+
+    match policy {
+        ReferrerPolicy::NoReferrer => None,
+        ReferrerPolicy::NoReferrerWhenDowngrade => {
+            if downgrade { None } else { Some(stripped_referrer) }
+        },
+        ReferrerPolicy::SameOrigin => {
+            if same_origin { Some(stripped_referrer) } else { None }
+        },
+        ReferrerPolicy::Origin => Some(origin_only),
+        ReferrerPolicy::StrictOrigin => {
+            if downgrade { None } else { Some(origin_only) }
+        },
+        ReferrerPolicy::OriginWhenCrossOrigin => {
+            if same_origin { Some(stripped_referrer) } else { Some(origin_only) }
+        },
+        ReferrerPolicy::StrictOriginWhenCrossOrigin => {
+            if downgrade {
+                None
+            } else if same_origin {
+                Some(stripped_referrer)
+            } else {
+                Some(origin_only)
+            }
+        },
+        ReferrerPolicy::UnsafeUrl => Some(stripped_referrer),
+    }
 }
 
 // fn modify_request_headers(headers: &mut Headers) -> {
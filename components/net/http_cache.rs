@@ -0,0 +1,224 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A minimal in-memory HTTP cache used by `http_network_or_cache_fetch` to
+//! satisfy `CacheMode::Default`/`ForceCache`/`OnlyIfCached` without hitting
+//! the network, and to drive conditional revalidation otherwise.
+
+use hyper::header::{Age, CacheControl, CacheDirective, Date, Expires, HttpDate};
+use hyper::header::{Headers, LastModified, Vary};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use net_traits::request::CacheMode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// A stored response, along with enough bookkeeping to decide freshness
+/// and to drive conditional revalidation.
+#[derive(Clone)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    pub stored_time: SystemTime,
+}
+
+struct CacheEntry {
+    /// The set of `(header name, header value)` pairs named by the stored
+    /// response's `Vary` header, used to decide whether the stored response
+    /// applies to a later request. Only one entry is kept per method and URL:
+    /// storing a response replaces any other variant for that URL.
+    /// Meaningless when `never_reusable` is set.
+    vary_fingerprint: Vec<(String, Option<String>)>,
+    /// Set for a `Vary: *` response: per RFC 7234 §4.1, such a response is
+    /// never reusable for a later request, since any aspect of the request
+    /// might have affected it. There is no finite fingerprint that can
+    /// represent "matches nothing", so this is tracked separately rather
+    /// than folded into `vary_fingerprint`.
+    never_reusable: bool,
+    response: CachedResponse,
+}
+
+/// A per-fetch-context HTTP cache, keyed by request method + URL.
+pub struct HttpCache {
+    entries: Vec<(Method, Url, CacheEntry)>,
+}
+
+impl HttpCache {
+    pub fn new() -> HttpCache {
+        HttpCache { entries: Vec::new() }
+    }
+
+    /// Look up a cached response whose `Vary` fingerprint matches the given
+    /// request headers.
+    pub fn get(&self, method: &Method, url: &Url, request_headers: &Headers) -> Option<&CachedResponse> {
+        self.entries.iter()
+            .filter(|&&(ref m, ref u, _)| m == method && u == url)
+            .find(|&&(_, _, ref entry)| !entry.never_reusable &&
+                  vary_fingerprint(request_headers, &header_names(&entry.response.headers)) == entry.vary_fingerprint)
+            .map(|&(_, _, ref entry)| &entry.response)
+    }
+
+    /// Store (or replace) a response for the given request, unless it is
+    /// uncacheable.
+    pub fn store(&mut self, method: &Method, url: &Url, request_headers: &Headers, response: CachedResponse) {
+        if !is_cacheable(method, response.status, &response.headers) {
+            return;
+        }
+
+        let never_reusable = matches!(response.headers.get::<Vary>(), Some(&Vary::Any));
+        let fingerprint = vary_fingerprint(request_headers, &header_names(&response.headers));
+        self.entries.retain(|&(ref m, ref u, _)| !(m == method && u == url));
+        self.entries.push((method.clone(), url.clone(), CacheEntry {
+            vary_fingerprint: fingerprint,
+            never_reusable: never_reusable,
+            response: response,
+        }));
+    }
+
+    /// Merge freshly-received headers (from a `304 Not Modified`) into the
+    /// stored entry for `method`/`url`, and return the merged response.
+    pub fn revalidate(&mut self, method: &Method, url: &Url, new_headers: &Headers) -> Option<CachedResponse> {
+        let entry = self.entries.iter_mut()
+            .find(|&&mut (ref m, ref u, _)| m == method && u == url);
+
+        entry.map(|&mut (_, _, ref mut entry)| {
+            for header in new_headers.iter() {
+                entry.response.headers.set_raw(header.name().to_owned(), vec![header.value_string().into_bytes()]);
+            }
+            entry.response.stored_time = SystemTime::now();
+            entry.response.clone()
+        })
+    }
+}
+
+/// The set of request-header values named by `vary_names`, used to decide
+/// whether a cached entry applies to a new request.
+fn vary_fingerprint(request_headers: &Headers, vary_names: &[String]) -> Vec<(String, Option<String>)> {
+    vary_names.iter()
+        .map(|name| (name.clone(), request_headers.get_raw(name).and_then(|raw| {
+            raw.get(0).map(|v| String::from_utf8_lossy(v).into_owned())
+        })))
+        .collect()
+}
+
+fn header_names(headers: &Headers) -> Vec<String> {
+    match headers.get::<Vary>() {
+        Some(&Vary::Any) | None => vec![],
+        Some(&Vary::Items(ref items)) => items.iter().map(|item| item.to_string()).collect(),
+    }
+}
+
+/// Only cache responses to safe methods with cacheable status codes, and
+/// never when the response forbids it.
+///
+/// Public so callers can check this *before* buffering a response's body (e.g.
+/// to decide whether blocking on `wait_until_done` ahead of a cache store is
+/// even worth doing).
+pub fn is_cacheable(method: &Method, status: StatusCode, headers: &Headers) -> bool {
+    let safe_method = matches!(*method, Method::Get | Method::Head);
+    let cacheable_status = matches!(status,
+        StatusCode::Ok | StatusCode::NonAuthoritativeInformation | StatusCode::NoContent |
+        StatusCode::MultipleChoices | StatusCode::MovedPermanently | StatusCode::NotFound |
+        StatusCode::MethodNotAllowed | StatusCode::Gone);
+    safe_method && cacheable_status && !has_no_store_directive(headers)
+}
+
+fn has_no_store_directive(headers: &Headers) -> bool {
+    has_directive(headers, |d| *d == CacheDirective::NoStore)
+}
+
+fn has_no_cache_directive(headers: &Headers) -> bool {
+    has_directive(headers, |d| *d == CacheDirective::NoCache)
+}
+
+fn has_must_revalidate_directive(headers: &Headers) -> bool {
+    has_directive(headers, |d| *d == CacheDirective::MustRevalidate)
+}
+
+fn has_directive<F: Fn(&CacheDirective) -> bool>(headers: &Headers, matches: F) -> bool {
+    headers.get::<CacheControl>().map_or(false, |&CacheControl(ref directives)| {
+        directives.iter().any(&matches)
+    })
+}
+
+/// [RFC 7234 §4.2.1](https://tools.ietf.org/html/rfc7234#section-4.2.1):
+/// `max-age`, else `Expires - Date`, else a heuristic fraction of
+/// `Date - Last-Modified`.
+pub fn freshness_lifetime(response: &CachedResponse) -> Duration {
+    let headers = &response.headers;
+
+    if let Some(&CacheControl(ref directives)) = headers.get::<CacheControl>() {
+        for directive in directives {
+            if let CacheDirective::MaxAge(secs) = *directive {
+                return Duration::from_secs(secs as u64);
+            }
+        }
+    }
+
+    let date = http_date_to_system_time(headers.get::<Date>().map(|d| &d.0));
+    let expires = headers.get::<Expires>().and_then(|e| http_date_to_system_time(Some(&e.0)));
+
+    if let (Some(date), Some(expires)) = (date, expires) {
+        if let Ok(lifetime) = expires.duration_since(date) {
+            return lifetime;
+        }
+        return Duration::from_secs(0);
+    }
+
+    // Heuristic: 10% of the age implied by Last-Modified, per RFC 7234 §4.2.2.
+    if let (Some(date), Some(&LastModified(ref last_modified))) = (date, headers.get::<LastModified>()) {
+        if let Some(last_modified) = http_date_to_system_time(Some(last_modified)) {
+            if let Ok(age) = date.duration_since(last_modified) {
+                return age / 10;
+            }
+        }
+    }
+
+    Duration::from_secs(0)
+}
+
+pub fn is_fresh(response: &CachedResponse) -> bool {
+    current_age(response) < freshness_lifetime(response)
+}
+
+/// [RFC 7234 §4.2.3](https://tools.ietf.org/html/rfc7234#section-4.2.3): how
+/// long a stored response has actually been stale-able for, which is the
+/// `Age` the origin server already reported plus however long it has since
+/// sat in this cache.
+fn current_age(response: &CachedResponse) -> Duration {
+    let age_value = response.headers.get::<Age>().map_or(Duration::from_secs(0), |&Age(secs)| {
+        Duration::from_secs(secs as u64)
+    });
+    let resident_time = SystemTime::now().duration_since(response.stored_time).unwrap_or(Duration::from_secs(0));
+    age_value + resident_time
+}
+
+/// Whether a cached entry must be revalidated before being reused for a
+/// request made with `cache_mode`: either it is already stale, the cached
+/// response forbids reuse without asking the server, or the request's
+/// `CacheMode::NoCache` demands it.
+pub fn response_needs_revalidation(response: &CachedResponse, cache_mode: CacheMode) -> bool {
+    !is_fresh(response) ||
+        cache_mode == CacheMode::NoCache ||
+        has_no_cache_directive(&response.headers) ||
+        has_must_revalidate_directive(&response.headers)
+}
+
+fn http_date_to_system_time(date: Option<&HttpDate>) -> Option<SystemTime> {
+    date.map(|date| UNIX_EPOCH + Duration::from_secs(date.0.to_timespec().sec.max(0) as u64))
+}
+
+/// Build the conditional-revalidation headers (`If-None-Match` /
+/// `If-Modified-Since`) for a stale (or forced-to-revalidate) cached entry.
+pub fn revalidation_headers(cached: &CachedResponse) -> Headers {
+    let mut headers = Headers::new();
+    if let Some(etag) = cached.headers.get_raw("etag") {
+        headers.set_raw("If-None-Match", etag.to_vec());
+    }
+    if let Some(&LastModified(ref last_modified)) = cached.headers.get::<LastModified>() {
+        headers.set_raw("If-Modified-Since", vec![last_modified.to_string().into_bytes()]);
+    }
+    headers
+}
@@ -0,0 +1,178 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Abstracts the network transport behind a trait so `http_network_fetch`'s
+//! surrounding fetch/CORS logic (cache-mode branching, preflight handling,
+//! redirect following) can be driven against canned responses instead of
+//! real sockets.
+
+use http_loader::{NetworkHttpRequestFactory, create_http_connector, obtain_response};
+use hyper::header::Headers;
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use resource_thread::CancellationListener;
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read};
+use std::sync::Mutex;
+use url::Url;
+
+/// A response to an [`HttpRequester::request`](trait.HttpRequester.html#tymethod.request) call.
+pub trait HttpResponse {
+    fn status(&self) -> StatusCode;
+    fn headers(&self) -> &Headers;
+    /// Consumes the response to hand over its body; callers read it to completion.
+    fn body(self: Box<Self>) -> Box<Read + Send>;
+}
+
+/// Performs (or fakes) the request/response exchange `http_network_fetch`
+/// needs, so the fetch algorithms around it can be exercised deterministically.
+pub trait HttpRequester {
+    fn request(&self, url: &Url, method: &Method, headers: &Headers, redirect_count: u32)
+                -> io::Result<Box<HttpResponse>>;
+}
+
+struct NetworkHttpResponse {
+    status: StatusCode,
+    headers: Headers,
+    body: Box<Read + Send>,
+}
+
+impl HttpResponse for NetworkHttpResponse {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    fn body(self: Box<Self>) -> Box<Read + Send> {
+        self.body
+    }
+}
+
+/// The production `HttpRequester`, backed by hyper over a real connection.
+pub struct NetworkHttpRequester;
+
+impl NetworkHttpRequester {
+    pub fn new() -> NetworkHttpRequester {
+        NetworkHttpRequester
+    }
+}
+
+impl HttpRequester for NetworkHttpRequester {
+    fn request(&self, url: &Url, method: &Method, headers: &Headers, redirect_count: u32)
+                -> io::Result<Box<HttpResponse>> {
+        let factory = NetworkHttpRequestFactory { connector: create_http_connector() };
+        let cancellation_listener = CancellationListener::new(None);
+
+        obtain_response(&factory, url, method, headers, &cancellation_listener, &None, method,
+                        &None, redirect_count, &None, "")
+            .map(|res| {
+                Box::new(NetworkHttpResponse {
+                    status: res.response.status,
+                    headers: res.response.headers.clone(),
+                    body: Box::new(res.response) as Box<Read + Send>,
+                }) as Box<HttpResponse>
+            })
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "network request failed"))
+    }
+}
+
+/// A single canned response for [`MockHttpRequester`](struct.MockHttpRequester.html).
+pub struct MockHttpResponse {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse for MockHttpResponse {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    fn body(self: Box<Self>) -> Box<Read + Send> {
+        Box::new(Cursor::new(self.body))
+    }
+}
+
+/// A test-only `HttpRequester` that hands back a fixed queue of canned
+/// responses, one per call to `request`, regardless of what's actually asked
+/// for — enough to drive scenarios like `304` revalidation, preflight
+/// `Access-Control-Allow-Methods` mismatches, and redirect handling without a
+/// socket.
+pub struct MockHttpRequester {
+    responses: Mutex<VecDeque<MockHttpResponse>>,
+}
+
+impl MockHttpRequester {
+    pub fn new(responses: Vec<MockHttpResponse>) -> MockHttpRequester {
+        MockHttpRequester { responses: Mutex::new(responses.into_iter().collect()) }
+    }
+}
+
+impl HttpRequester for MockHttpRequester {
+    fn request(&self, _url: &Url, _method: &Method, _headers: &Headers, _redirect_count: u32)
+                -> io::Result<Box<HttpResponse>> {
+        self.responses.lock().unwrap().pop_front()
+            .map(|response| Box::new(response) as Box<HttpResponse>)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no more mock responses queued"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HttpRequester, MockHttpRequester, MockHttpResponse};
+    use hyper::header::{Headers, Location};
+    use hyper::method::Method;
+    use hyper::status::StatusCode;
+    use std::io::Read;
+    use url::Url;
+
+    fn dummy_url() -> Url {
+        Url::parse("http://example.com/").unwrap()
+    }
+
+    #[test]
+    fn hands_back_queued_responses_in_order() {
+        let mut not_modified_headers = Headers::new();
+        not_modified_headers.set_raw("etag", vec![b"\"v1\"".to_vec()]);
+        let mut redirect_headers = Headers::new();
+        redirect_headers.set(Location("http://example.com/next".to_owned()));
+
+        let requester = MockHttpRequester::new(vec![
+            MockHttpResponse { status: StatusCode::NotModified, headers: not_modified_headers, body: vec![] },
+            MockHttpResponse { status: StatusCode::Found, headers: redirect_headers, body: vec![] },
+            MockHttpResponse { status: StatusCode::Ok, headers: Headers::new(), body: b"hello".to_vec() },
+        ]);
+
+        let first = requester.request(&dummy_url(), &Method::Get, &Headers::new(), 0).unwrap();
+        assert_eq!(first.status(), StatusCode::NotModified);
+        assert!(first.headers().get_raw("etag").is_some());
+
+        let second = requester.request(&dummy_url(), &Method::Get, &Headers::new(), 0).unwrap();
+        assert_eq!(second.status(), StatusCode::Found);
+        assert!(second.headers().has::<Location>());
+
+        let third = requester.request(&dummy_url(), &Method::Get, &Headers::new(), 0).unwrap();
+        assert_eq!(third.status(), StatusCode::Ok);
+        let mut body = vec![];
+        third.body().read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"hello");
+    }
+
+    #[test]
+    fn errors_once_the_queue_is_exhausted() {
+        let requester = MockHttpRequester::new(vec![
+            MockHttpResponse { status: StatusCode::Ok, headers: Headers::new(), body: vec![] },
+        ]);
+
+        assert!(requester.request(&dummy_url(), &Method::Get, &Headers::new(), 0).is_ok());
+        assert!(requester.request(&dummy_url(), &Method::Get, &Headers::new(), 0).is_err());
+    }
+}
@@ -0,0 +1,10 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Only part of `net_traits` is present in this tree: the `request` module's
+//! `ServiceWorkersMode` and `ReferrerPolicy` enums. The rest of the crate that
+//! `net` uses (the `Request`/`Response` structs themselves, `AsyncFetchListener`,
+//! etc.) is not here.
+
+pub mod request;
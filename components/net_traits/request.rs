@@ -0,0 +1,31 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// [Service-workers mode](https://fetch.spec.whatwg.org/#concept-request-service-workers-mode):
+/// which registered service workers, if any, are allowed to intercept a request.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ServiceWorkersMode {
+    /// Both the client's own scope and foreign scopes may intercept.
+    All,
+    /// Only a foreign scope (one the request did not originate from) may intercept;
+    /// used for requests the client's own service worker itself issues.
+    Foreign,
+    /// No service worker may intercept; set by HTTP fetch's Step 4, Substep 2, so a
+    /// request is only ever offered to a service worker once.
+    None,
+}
+
+/// [Referrer policy](https://www.w3.org/TR/referrer-policy/#referrer-policies), controlling
+/// how much of the referrer URL `determine_request_referrer` is allowed to expose.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ReferrerPolicy {
+    NoReferrer,
+    NoReferrerWhenDowngrade,
+    SameOrigin,
+    Origin,
+    StrictOrigin,
+    OriginWhenCrossOrigin,
+    StrictOriginWhenCrossOrigin,
+    UnsafeUrl,
+}